@@ -1,10 +1,6 @@
-use lazy_static::lazy_static;
+use crate::http::HTTP_CLIENT;
 
-static BASE_URL: &'static str = "https://api.amazonalexa.com";
-
-lazy_static! {
-    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
-}
+static BASE_URL: &str = "https://api.amazonalexa.com";
 
 pub mod skill_package_management {
     use serde::{Deserialize, Serialize};
@@ -23,18 +19,37 @@ pub mod skill_package_management {
         pub export_id: String,
     }
 
+    #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum ExportStatus {
+        InProgress,
+        Succeeded,
+        Failed,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ExportStatusSkill {
+        pub location: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ExportStatusResponse {
+        pub status: ExportStatus,
+        pub skill: Option<ExportStatusSkill>,
+    }
+
+    const EXPORT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+    const EXPORT_POLL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
     pub async fn export_skill_package(
         profile_name: &str,
+        client_id: &str,
         skill_id: &str,
         stage: SkillStage,
-    ) -> Result<ExportSkillPackageResponse, reqwest::Error> {
-        let profile = crate::config::CONFIG
-            .get_profile(profile_name)
-            .expect(format!("Profile '{}' not found in config", profile_name).as_str());
+    ) -> Result<ExportSkillPackageResponse, super::errors::SkillPackageError> {
+        let mut config = crate::config::Config::new()?;
 
-        if !profile.is_valid() {
-            panic!("Profile '{}' is not valid:\n{:?}", profile_name, profile);
-        }
+        let access_token = config.refreshed_profile(profile_name, client_id).await?;
 
         let url = format!(
             "{}/v1/skills/{}/stages/{}/exports",
@@ -43,21 +58,354 @@ pub mod skill_package_management {
             stage,
         );
 
-        let res = super::HTTP_CLIENT
-            .post(&url)
-            .header(
-                "Authorization",
-                format!("Bearer {}", profile.access_token.clone().unwrap()),
+        let res = crate::http::ensure_success(
+            crate::http::execute(
+                super::HTTP_CLIENT
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", access_token)),
             )
-            .send()
-            .await?;
+            .await?,
+        )
+        .await?;
 
         let body = res.json::<ExportSkillPackageResponse>().await?;
 
         Ok(body)
     }
+
+    /// Fetches the current status of a skill package export previously
+    /// started with [`export_skill_package`] (a single request; see
+    /// [`download_skill_package`] for the polling loop).
+    pub async fn get_export_status(
+        profile_name: &str,
+        client_id: &str,
+        export_id: &str,
+    ) -> Result<ExportStatusResponse, super::errors::SkillPackageError> {
+        let mut config = crate::config::Config::new()?;
+
+        let access_token = config.refreshed_profile(profile_name, client_id).await?;
+
+        let url = format!("{}/v1/skills/exports/{}", super::BASE_URL, export_id);
+
+        let res = crate::http::ensure_success(
+            crate::http::execute(
+                super::HTTP_CLIENT
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", access_token)),
+            )
+            .await?,
+        )
+        .await?;
+
+        let body = res.json::<ExportStatusResponse>().await?;
+
+        Ok(body)
+    }
+
+    /// Exports `skill_id`, polls until the export finishes, then downloads the
+    /// resulting skill package zip into memory and unpacks it into `dest_dir`.
+    /// The zip archive itself is not written to disk.
+    pub async fn download_skill_package(
+        profile_name: &str,
+        client_id: &str,
+        skill_id: &str,
+        stage: SkillStage,
+        dest_dir: &std::path::Path,
+    ) -> Result<(), super::errors::SkillPackageError> {
+        let export = export_skill_package(profile_name, client_id, skill_id, stage).await?;
+
+        let deadline = tokio::time::Instant::now() + EXPORT_POLL_TIMEOUT;
+
+        let download_url = loop {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(super::errors::SkillPackageError::ExportTimedOut);
+            }
+
+            let status = get_export_status(profile_name, client_id, &export.export_id).await?;
+
+            match status.status {
+                ExportStatus::InProgress => {
+                    tokio::time::sleep(EXPORT_POLL_INTERVAL).await;
+                    continue;
+                }
+                ExportStatus::Succeeded => {
+                    break status
+                        .skill
+                        .ok_or(super::errors::SkillPackageError::ExportFailed)?
+                        .location;
+                }
+                ExportStatus::Failed => {
+                    return Err(super::errors::SkillPackageError::ExportFailed)
+                }
+            }
+        };
+
+        let res = crate::http::ensure_success(
+            crate::http::execute(super::HTTP_CLIENT.get(&download_url)).await?,
+        )
+        .await?;
+
+        let zip_bytes = res.bytes().await?;
+        let dest_dir = dest_dir.to_path_buf();
+
+        tokio::task::spawn_blocking(move || -> Result<(), super::errors::SkillPackageError> {
+            std::fs::create_dir_all(&dest_dir)?;
+
+            let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zip_bytes))
+                .map_err(|_| super::errors::SkillPackageError::UnzipError)?;
+
+            archive
+                .extract(&dest_dir)
+                .map_err(|_| super::errors::SkillPackageError::UnzipError)?;
+
+            Ok(())
+        })
+        .await
+        .map_err(|_| super::errors::SkillPackageError::UnzipError)??;
+
+        Ok(())
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct CreateUploadResponse {
+        pub upload_url: String,
+        pub expires_at: String,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ImportSkillPackageResponse {
+        pub location: String,
+        pub import_id: String,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum ImportStatus {
+        InProgress,
+        Succeeded,
+        Failed,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ImportStatusResource {
+        pub name: String,
+        pub status: ImportStatus,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ImportStatusSkill {
+        pub resources: Vec<ImportStatusResource>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct ImportStatusResponse {
+        pub status: ImportStatus,
+        pub skill: Option<ImportStatusSkill>,
+    }
+
+    const IMPORT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+    const IMPORT_POLL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+
+    async fn create_upload_url(
+        access_token: &str,
+    ) -> Result<CreateUploadResponse, super::errors::SkillPackageError> {
+        let url = format!("{}/v1/skills/uploads", super::BASE_URL);
+
+        let res = crate::http::ensure_success(
+            crate::http::execute(
+                super::HTTP_CLIENT
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", access_token)),
+            )
+            .await?,
+        )
+        .await?;
+
+        let body = res.json::<CreateUploadResponse>().await?;
+
+        Ok(body)
+    }
+
+    async fn create_import(
+        access_token: &str,
+        skill_id: &str,
+        upload_url: &str,
+    ) -> Result<ImportSkillPackageResponse, super::errors::SkillPackageError> {
+        let url = format!("{}/v1/skills/{}/imports", super::BASE_URL, skill_id);
+
+        let res = crate::http::ensure_success(
+            crate::http::execute(
+                super::HTTP_CLIENT
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", access_token))
+                    .json(&serde_json::json!({ "location": upload_url })),
+            )
+            .await?,
+        )
+        .await?;
+
+        let body = res.json::<ImportSkillPackageResponse>().await?;
+
+        Ok(body)
+    }
+
+    async fn get_import_status(
+        profile_name: &str,
+        client_id: &str,
+        skill_id: &str,
+        import_id: &str,
+    ) -> Result<ImportStatusResponse, super::errors::SkillPackageError> {
+        let mut config = crate::config::Config::new()?;
+
+        let access_token = config.refreshed_profile(profile_name, client_id).await?;
+
+        let url = format!(
+            "{}/v1/skills/{}/imports/{}",
+            super::BASE_URL,
+            skill_id,
+            import_id,
+        );
+
+        let res = crate::http::ensure_success(
+            crate::http::execute(
+                super::HTTP_CLIENT
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", access_token)),
+            )
+            .await?,
+        )
+        .await?;
+
+        let body = res.json::<ImportStatusResponse>().await?;
+
+        Ok(body)
+    }
+
+    fn zip_directory(dir: &std::path::Path) -> Result<Vec<u8>, super::errors::SkillPackageError> {
+        let mut buf = std::io::Cursor::new(Vec::new());
+
+        {
+            let mut writer = zip::ZipWriter::new(&mut buf);
+            let options = zip::write::FileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated);
+
+            add_dir_to_zip(&mut writer, dir, dir, options)?;
+
+            writer
+                .finish()
+                .map_err(|_| super::errors::SkillPackageError::ZipError)?;
+        }
+
+        Ok(buf.into_inner())
+    }
+
+    fn add_dir_to_zip<W: std::io::Write + std::io::Seek>(
+        writer: &mut zip::ZipWriter<W>,
+        root: &std::path::Path,
+        dir: &std::path::Path,
+        options: zip::write::FileOptions,
+    ) -> Result<(), super::errors::SkillPackageError> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                add_dir_to_zip(writer, root, &path, options)?;
+                continue;
+            }
+
+            let name = path
+                .strip_prefix(root)
+                .expect("walked path is under root")
+                .to_string_lossy()
+                .replace('\\', "/");
+
+            writer
+                .start_file(name, options)
+                .map_err(|_| super::errors::SkillPackageError::ZipError)?;
+
+            let contents = std::fs::read(&path)?;
+            std::io::Write::write_all(writer, &contents)?;
+        }
+
+        Ok(())
+    }
+
+    /// Zips `package_dir` (skill manifest + interaction models), uploads it,
+    /// and imports it into `skill_id`, polling until the import resolves.
+    /// The returned [`ImportStatusResponse`] carries the per-resource
+    /// build/validation results whether the import succeeded or failed.
+    pub async fn import_skill_package(
+        profile_name: &str,
+        client_id: &str,
+        skill_id: &str,
+        package_dir: &std::path::Path,
+    ) -> Result<ImportStatusResponse, super::errors::SkillPackageError> {
+        let mut config = crate::config::Config::new()?;
+        let access_token = config.refreshed_profile(profile_name, client_id).await?;
+
+        let upload = create_upload_url(&access_token).await?;
+
+        let package_bytes = zip_directory(package_dir)?;
+
+        crate::http::ensure_success(
+            crate::http::execute(super::HTTP_CLIENT.put(&upload.upload_url).body(package_bytes))
+                .await?,
+        )
+        .await?;
+
+        let import = create_import(&access_token, skill_id, &upload.upload_url).await?;
+
+        let deadline = tokio::time::Instant::now() + IMPORT_POLL_TIMEOUT;
+
+        loop {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(super::errors::SkillPackageError::ImportTimedOut);
+            }
+
+            let status =
+                get_import_status(profile_name, client_id, skill_id, &import.import_id).await?;
+
+            match status.status {
+                ImportStatus::InProgress => {
+                    tokio::time::sleep(IMPORT_POLL_INTERVAL).await;
+                    continue;
+                }
+                ImportStatus::Succeeded | ImportStatus::Failed => return Ok(status),
+            }
+        }
+    }
 }
 
 pub mod errors {
     use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum SkillPackageError {
+        #[error("Failed to make the request.")]
+        FetchError(#[from] reqwest::Error),
+
+        #[error("The HTTP request failed")]
+        RequestError(#[from] crate::http::errors::RequestError),
+
+        #[error("Failed to load or refresh the profile's credentials")]
+        ConfigError(#[from] crate::config::errors::ConfigError),
+
+        #[error("A filesystem error occurred")]
+        IoError(#[from] std::io::Error),
+
+        #[error("The skill package export failed")]
+        ExportFailed,
+
+        #[error("Timed out waiting for the skill package export to finish")]
+        ExportTimedOut,
+
+        #[error("Failed to unpack the downloaded skill package")]
+        UnzipError,
+
+        #[error("Failed to zip the local skill package")]
+        ZipError,
+
+        #[error("Timed out waiting for the skill package import to finish")]
+        ImportTimedOut,
+    }
 }
@@ -1,10 +1,6 @@
-use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-lazy_static! {
-    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
-}
+use crate::http::HTTP_CLIENT;
 
 pub async fn get_codepair(
     client_id: String,
@@ -17,15 +13,16 @@ pub async fn get_codepair(
         }
     );
 
-    let res = HTTP_CLIENT
-        .post("https://api.amazon.com/auth/o2/create/codepair")
-        .header(
-            "Content-Type",
-            "application/x-www-form-urlencoded;charset=UTF-8",
-        )
-        .form(&req)
-        .send()
-        .await?;
+    let res = crate::http::execute(
+        HTTP_CLIENT
+            .post("https://api.amazon.com/auth/o2/create/codepair")
+            .header(
+                "Content-Type",
+                "application/x-www-form-urlencoded;charset=UTF-8",
+            )
+            .form(&req),
+    )
+    .await?;
 
     let body = res.json::<serde_json::Value>().await?;
 
@@ -37,7 +34,7 @@ pub async fn get_codepair(
         return Ok(code_pair);
     }
 
-    panic!("Unknown response: {:?}", body.to_string());
+    Err(errors::AuthorizationError::UnexpectedResponse(body.to_string()))
 }
 
 pub async fn perform_code_exchange(
@@ -52,15 +49,16 @@ pub async fn perform_code_exchange(
         }
     );
 
-    let res = HTTP_CLIENT
-        .post("https://api.amazon.com/auth/o2/token")
-        .header(
-            "Content-Type",
-            "application/x-www-form-urlencoded;charset=UTF-8",
-        )
-        .form(&req)
-        .send()
-        .await?;
+    let res = crate::http::execute(
+        HTTP_CLIENT
+            .post("https://api.amazon.com/auth/o2/token")
+            .header(
+                "Content-Type",
+                "application/x-www-form-urlencoded;charset=UTF-8",
+            )
+            .form(&req),
+    )
+    .await?;
 
     let body = res.json::<serde_json::Value>().await?;
 
@@ -72,7 +70,165 @@ pub async fn perform_code_exchange(
         return Ok(token_res);
     }
 
-    panic!("Unknown response: {:?}", body.to_string());
+    Err(errors::AuthorizationError::UnexpectedResponse(body.to_string()))
+}
+
+/// Drives the device-authorization flow to completion for `profile_name`.
+///
+/// Requests a code pair, prints the verification URL and user code so the
+/// caller can complete the authorization in a browser, then polls the token
+/// endpoint until the user finishes (or the code pair expires). On success
+/// the resulting tokens are written into a `ConfigProfile` named
+/// `profile_name` and persisted to disk.
+pub async fn login(
+    profile_name: String,
+    client_id: String,
+    vendor_id: String,
+) -> Result<(), errors::AuthorizationError> {
+    let code_pair = get_codepair(client_id.clone()).await?;
+
+    println!(
+        "To sign in, go to {} and enter the code: {}",
+        code_pair.verification_uri, code_pair.user_code
+    );
+
+    let deadline =
+        tokio::time::Instant::now() + tokio::time::Duration::from_secs(code_pair.expires_in as u64);
+    let mut interval = tokio::time::Duration::from_secs(code_pair.interval);
+
+    let token_res = loop {
+        if tokio::time::Instant::now() >= deadline {
+            return Err(errors::AuthorizationError::LoginTimedOut);
+        }
+
+        tokio::time::sleep(interval).await;
+
+        match perform_code_exchange(code_pair.user_code.clone(), code_pair.device_code.clone()).await
+        {
+            Ok(token_res) => break token_res,
+            Err(errors::AuthorizationError::AuthorizationPending) => continue,
+            Err(errors::AuthorizationError::SlowDown) => {
+                interval += tokio::time::Duration::from_secs(5);
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+    };
+
+    let mut config = crate::config::Config::new()?;
+
+    let mut profile = crate::config::ConfigProfile::new(profile_name);
+    profile.init(
+        token_res.access_token,
+        token_res.refresh_token,
+        token_res.expires_in,
+        vendor_id,
+    );
+
+    config.upsert_profile(&profile)?;
+
+    Ok(())
+}
+
+/// Signs in via the OAuth authorization-code grant with PKCE: opens the
+/// authorize page in the system browser, catches the redirect on a one-shot
+/// localhost listener, and exchanges the resulting code for tokens. Nicer
+/// than [`login`] on a desktop with a browser, since it doesn't require the
+/// user to copy a code by hand.
+pub async fn login_with_browser(
+    profile_name: String,
+    client_id: String,
+    vendor_id: String,
+) -> Result<(), errors::AuthorizationError> {
+    let code_verifier = pkce::generate_code_verifier();
+    let code_challenge = pkce::code_challenge(&code_verifier);
+    let state = pkce::generate_state();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let redirect_uri = format!(
+        "http://127.0.0.1:{}/callback",
+        listener.local_addr()?.port()
+    );
+
+    let mut authorize_url = reqwest::Url::parse("https://www.amazon.com/ap/oa")
+        .expect("authorize URL is valid");
+    authorize_url
+        .query_pairs_mut()
+        .append_pair("client_id", &client_id)
+        .append_pair("scope", "profile")
+        .append_pair("response_type", "code")
+        .append_pair("redirect_uri", &redirect_uri)
+        .append_pair("state", &state)
+        .append_pair("code_challenge", &code_challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    webbrowser::open(authorize_url.as_str())
+        .map_err(|_| errors::AuthorizationError::BrowserError)?;
+
+    let (code, returned_state) = tokio::task::spawn_blocking(move || pkce::await_redirect(listener))
+        .await
+        .map_err(|_| errors::AuthorizationError::RedirectError)??;
+
+    if returned_state != state {
+        return Err(errors::AuthorizationError::StateMismatch);
+    }
+
+    let token_res =
+        perform_authorization_code_exchange(client_id, code, redirect_uri, code_verifier).await?;
+
+    let mut config = crate::config::Config::new()?;
+
+    let mut profile = crate::config::ConfigProfile::new(profile_name);
+    profile.init(
+        token_res.access_token,
+        token_res.refresh_token,
+        token_res.expires_in,
+        vendor_id,
+    );
+
+    config.upsert_profile(&profile)?;
+
+    Ok(())
+}
+
+pub async fn perform_authorization_code_exchange(
+    client_id: String,
+    code: String,
+    redirect_uri: String,
+    code_verifier: String,
+) -> Result<models::TokenResponse, errors::AuthorizationError> {
+    let req = json!(
+        {
+            "grant_type": "authorization_code",
+            "code": code,
+            "redirect_uri": redirect_uri,
+            "client_id": client_id,
+            "code_verifier": code_verifier,
+        }
+    );
+
+    let res = crate::http::execute(
+        HTTP_CLIENT
+            .post("https://api.amazon.com/auth/o2/token")
+            .header(
+                "Content-Type",
+                "application/x-www-form-urlencoded;charset=UTF-8",
+            )
+            .form(&req),
+    )
+    .await?;
+
+    let body = res.json::<serde_json::Value>().await?;
+
+    if let Ok(err_res) = serde_json::from_value::<models::ErrorResponse>(body.clone()) {
+        return Err(errors::AuthorizationError::from_error_response(&err_res));
+    }
+
+    if let Ok(token_res) = serde_json::from_value::<models::TokenResponse>(body.clone()) {
+        return Ok(token_res);
+    }
+
+    Err(errors::AuthorizationError::UnexpectedResponse(body.to_string()))
 }
 
 pub async fn perform_token_refresh(
@@ -87,15 +243,16 @@ pub async fn perform_token_refresh(
         }
     );
 
-    let res = HTTP_CLIENT
-        .post("https://api.amazon.com/auth/o2/token")
-        .header(
-            "Content-Type",
-            "application/x-www-form-urlencoded;charset=UTF-8",
-        )
-        .form(&req)
-        .send()
-        .await?;
+    let res = crate::http::execute(
+        HTTP_CLIENT
+            .post("https://api.amazon.com/auth/o2/token")
+            .header(
+                "Content-Type",
+                "application/x-www-form-urlencoded;charset=UTF-8",
+            )
+            .form(&req),
+    )
+    .await?;
 
     let body = res.json::<serde_json::Value>().await?;
 
@@ -107,7 +264,7 @@ pub async fn perform_token_refresh(
         return Ok(token_res);
     }
 
-    panic!("Unknown response: {:?}", body.to_string());
+    Err(errors::AuthorizationError::UnexpectedResponse(body.to_string()))
 }
 
 pub mod errors {
@@ -147,11 +304,52 @@ pub mod errors {
         )]
         ExpiredToken,
 
+        #[error("Timed out waiting for the user to authorize the device")]
+        LoginTimedOut,
+
         #[error("Failed to make the request.")]
         FetchError(#[from] reqwest::Error),
 
         #[error("Failed to parse the response")]
         ParseError(#[from] serde_json::Error),
+
+        #[error("The HTTP request failed")]
+        RequestError(#[from] crate::http::errors::RequestError),
+
+        #[error("Failed to load or persist the config")]
+        ConfigError(Box<crate::config::errors::ConfigError>),
+
+        #[error("Failed to run the local redirect server")]
+        IoError(#[from] std::io::Error),
+
+        #[error("Failed to open the system browser")]
+        BrowserError,
+
+        #[error("Received an unexpected or incomplete redirect from the authorization server")]
+        RedirectError,
+
+        #[error(
+            "The `state` parameter returned by the redirect did not match the one that was sent"
+        )]
+        StateMismatch,
+
+        #[error("The refresh token is invalid, expired, revoked, or was issued to another client")]
+        InvalidGrant,
+
+        #[error("Client authentication failed (invalid client id or secret)")]
+        InvalidClient,
+
+        #[error("Received an unrecognized OAuth error: {0}")]
+        UnknownError(String),
+
+        #[error("Received an unexpected response body: {0}")]
+        UnexpectedResponse(String),
+    }
+
+    impl From<crate::config::errors::ConfigError> for AuthorizationError {
+        fn from(err: crate::config::errors::ConfigError) -> Self {
+            AuthorizationError::ConfigError(Box::new(err))
+        }
     }
 
     impl AuthorizationError {
@@ -167,12 +365,185 @@ pub mod errors {
                 "authorization_pending" => AuthorizationError::AuthorizationPending,
                 "slow_down" => AuthorizationError::SlowDown,
                 "expired_token" => AuthorizationError::ExpiredToken,
-                _ => panic!("Unknown error type: {}", res.error),
+                "invalid_grant" => AuthorizationError::InvalidGrant,
+                "invalid_client" => AuthorizationError::InvalidClient,
+                _ => AuthorizationError::UnknownError(res.error.clone()),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn error_response(error: &str) -> super::super::models::ErrorResponse {
+            super::super::models::ErrorResponse {
+                error: error.to_string(),
+                error_description: None,
+            }
+        }
+
+        #[test]
+        fn maps_invalid_grant() {
+            assert!(matches!(
+                AuthorizationError::from_error_response(&error_response("invalid_grant")),
+                AuthorizationError::InvalidGrant
+            ));
+        }
+
+        #[test]
+        fn maps_invalid_client() {
+            assert!(matches!(
+                AuthorizationError::from_error_response(&error_response("invalid_client")),
+                AuthorizationError::InvalidClient
+            ));
+        }
+
+        #[test]
+        fn maps_known_errors() {
+            assert!(matches!(
+                AuthorizationError::from_error_response(&error_response("slow_down")),
+                AuthorizationError::SlowDown
+            ));
+            assert!(matches!(
+                AuthorizationError::from_error_response(&error_response("expired_token")),
+                AuthorizationError::ExpiredToken
+            ));
+        }
+
+        #[test]
+        fn falls_back_to_unknown_error_instead_of_panicking() {
+            match AuthorizationError::from_error_response(&error_response("something_new")) {
+                AuthorizationError::UnknownError(err) => assert_eq!(err, "something_new"),
+                other => panic!("expected UnknownError, got {:?}", other),
             }
         }
     }
 }
 
+mod pkce {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use rand::RngCore;
+    use sha2::{Digest, Sha256};
+    use std::io::{BufRead, Write};
+
+    const VERIFIER_CHARS: &[u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+
+    pub fn generate_code_verifier() -> String {
+        let mut rng = rand::thread_rng();
+        (0..128)
+            .map(|_| VERIFIER_CHARS[(rng.next_u32() as usize) % VERIFIER_CHARS.len()] as char)
+            .collect()
+    }
+
+    pub fn code_challenge(code_verifier: &str) -> String {
+        let digest = Sha256::digest(code_verifier.as_bytes());
+        URL_SAFE_NO_PAD.encode(digest)
+    }
+
+    pub fn generate_state() -> String {
+        let mut bytes = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Blocks waiting for the single redirect from the authorize page, then
+    /// returns the `code` and `state` query parameters it carried.
+    pub fn await_redirect(
+        listener: std::net::TcpListener,
+    ) -> Result<(String, String), super::errors::AuthorizationError> {
+        let (mut stream, _) = listener.accept()?;
+
+        let mut request_line = String::new();
+        std::io::BufReader::new(&stream).read_line(&mut request_line)?;
+
+        let path = request_line
+            .split_whitespace()
+            .nth(1)
+            .ok_or(super::errors::AuthorizationError::RedirectError)?;
+
+        let url = reqwest::Url::parse(&format!("http://127.0.0.1{}", path))
+            .map_err(|_| super::errors::AuthorizationError::RedirectError)?;
+
+        let mut code = None;
+        let mut state = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "code" => code = Some(value.into_owned()),
+                "state" => state = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        let body = "<html><body>Signed in. You may close this window and return to the terminal.</body></html>";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: text/html\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes())?;
+
+        match (code, state) {
+            (Some(code), Some(state)) => Ok((code, state)),
+            _ => Err(super::errors::AuthorizationError::RedirectError),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn code_challenge_matches_known_vector() {
+            // RFC 7636 appendix B example.
+            let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+            assert_eq!(
+                code_challenge(verifier),
+                "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+            );
+        }
+
+        #[test]
+        fn await_redirect_parses_code_and_state() {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let client = std::thread::spawn(move || {
+                let mut stream = std::net::TcpStream::connect(addr).unwrap();
+                stream
+                    .write_all(b"GET /callback?code=abc123&state=xyz789 HTTP/1.1\r\n\r\n")
+                    .unwrap();
+            });
+
+            let (code, state) = await_redirect(listener).unwrap();
+            client.join().unwrap();
+
+            assert_eq!(code, "abc123");
+            assert_eq!(state, "xyz789");
+        }
+
+        #[test]
+        fn await_redirect_errors_on_missing_params() {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let client = std::thread::spawn(move || {
+                let mut stream = std::net::TcpStream::connect(addr).unwrap();
+                stream.write_all(b"GET /callback HTTP/1.1\r\n\r\n").unwrap();
+            });
+
+            let result = await_redirect(listener);
+            client.join().unwrap();
+
+            assert!(matches!(
+                result,
+                Err(super::super::errors::AuthorizationError::RedirectError)
+            ));
+        }
+    }
+}
+
 pub mod models {
     use serde::{Deserialize, Serialize};
 
@@ -3,11 +3,12 @@ use home::home_dir;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
+use secret::Secret;
+
 lazy_static! {
     static ref CONFIG_PATH: std::path::PathBuf = {
         let homedir = home_dir().expect("Unable to find home directory");
-        let config_dir = homedir.join(".alexa-util").join("config.json");
-        config_dir
+        homedir.join(".alexa-util").join("config.json")
     };
     pub static ref CONFIG: Config = Config::new().expect("Unable to load config");
 }
@@ -47,6 +48,39 @@ impl Config {
         Ok(())
     }
 
+    /// Adds `profile`, or overwrites the existing profile of the same name.
+    /// Used by the login flows, where re-authenticating an existing profile
+    /// (switching accounts, refreshing by hand) should update it in place
+    /// rather than fail with `AlreadyExists`.
+    pub fn upsert_profile(&mut self, profile: &ConfigProfile) -> Result<(), errors::ConfigError> {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile.clone(),
+            None => self.profiles.push(profile.clone()),
+        }
+
+        self.write()?;
+        Ok(())
+    }
+
+    /// Returns a valid bearer access token for the named profile, refreshing
+    /// and persisting it first if it has expired.
+    pub async fn refreshed_profile(
+        &mut self,
+        name: &str,
+        client_id: &str,
+    ) -> Result<String, errors::ConfigError> {
+        let profile = self
+            .profiles
+            .iter_mut()
+            .find(|p| p.name == name)
+            .ok_or(errors::ConfigError::NotFound)?;
+
+        let access_token = profile.ensure_valid(client_id).await?;
+        self.write()?;
+
+        Ok(access_token)
+    }
+
     pub fn write(&self) -> Result<(), errors::ConfigError> {
         let config_path = CONFIG_PATH.clone();
         std::fs::create_dir_all(config_path.parent().unwrap())?;
@@ -85,8 +119,8 @@ impl Drop for Config {
 pub struct ConfigProfile {
     pub name: String,
     pub vendor_id: Option<String>,
-    pub access_token: Option<String>,
-    pub refresh_token: Option<String>,
+    pub access_token: Secret,
+    pub refresh_token: Secret,
     pub token_type: String,
 
     #[serde(with = "chrono::serde::ts_seconds_option")]
@@ -98,8 +132,8 @@ impl ConfigProfile {
         Self {
             name,
             vendor_id: None,
-            access_token: None,
-            refresh_token: None,
+            access_token: Secret::none(),
+            refresh_token: Secret::none(),
             token_type: String::from("Bearer"),
             expires_at: None,
         }
@@ -113,8 +147,8 @@ impl ConfigProfile {
         vendor_id: String,
     ) {
         self.vendor_id = Some(vendor_id);
-        self.access_token = Some(access_token);
-        self.refresh_token = Some(refresh_token);
+        self.access_token = Secret::some(access_token);
+        self.refresh_token = Secret::some(refresh_token);
         self.expires_at = Some(Utc::now() + chrono::Duration::seconds(expires_in as i64));
     }
 
@@ -129,6 +163,37 @@ impl ConfigProfile {
                 None => false,
             }
     }
+
+    fn refresh(&mut self, access_token: String, refresh_token: String, expires_in: u64) {
+        self.access_token = Secret::some(access_token);
+        self.refresh_token = Secret::some(refresh_token);
+        self.expires_at = Some(Utc::now() + chrono::Duration::seconds(expires_in as i64));
+    }
+
+    /// Returns a valid bearer access token for this profile, refreshing it
+    /// first if it has expired and a `refresh_token` is available.
+    pub async fn ensure_valid(&mut self, client_id: &str) -> Result<String, errors::ConfigError> {
+        if self.is_valid() {
+            return Ok(self.access_token.expose().unwrap().to_string());
+        }
+
+        let refresh_token = self
+            .refresh_token
+            .expose()
+            .map(str::to_string)
+            .ok_or(errors::ConfigError::NotAuthenticated)?;
+
+        let token_res =
+            crate::auth::perform_token_refresh(client_id.to_string(), refresh_token).await?;
+
+        self.refresh(
+            token_res.access_token,
+            token_res.refresh_token,
+            token_res.expires_in,
+        );
+
+        Ok(self.access_token.expose().unwrap().to_string())
+    }
 }
 
 pub mod errors {
@@ -142,10 +207,242 @@ pub mod errors {
         #[error("Config not found")]
         NotFound,
 
+        #[error("Profile has no valid access token and no refresh token to exchange for one")]
+        NotAuthenticated,
+
         #[error("A filesystem error occurred")]
         IoError(#[from] std::io::Error),
 
         #[error("Failed to either serialize or deserialize config")]
         SerdeError(#[from] serde_json::Error),
+
+        #[error("Failed to refresh the access token")]
+        AuthError(#[from] crate::auth::errors::AuthorizationError),
+
+        #[error("Failed to decrypt config secrets; the key is wrong or the data has been tampered with")]
+        DecryptError,
+    }
+}
+
+pub mod secret {
+    use aes_gcm::aead::{Aead, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Nonce};
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+    use lazy_static::lazy_static;
+    use rand::RngCore;
+    use serde::{Deserialize, Serialize};
+    use sha2::Sha256;
+
+    const KDF_SALT: &[u8] = b"alexa-util/config-secret/v1";
+    const KDF_ROUNDS: u32 = 100_000;
+
+    lazy_static! {
+        static ref KEY_PATH: std::path::PathBuf = {
+            let homedir = home::home_dir().expect("Unable to find home directory");
+            homedir.join(".alexa-util").join("key")
+        };
+    }
+
+    /// A string that is encrypted with AES-256-GCM whenever it is
+    /// serialized and transparently decrypted on deserialization, so it
+    /// never touches disk in plaintext. Its `Debug` impl redacts the value
+    /// so it can't leak through `panic!`/`eprintln!` either.
+    #[derive(Clone, Eq, PartialOrd, Ord, PartialEq, Hash)]
+    pub struct Secret(Option<String>);
+
+    impl Secret {
+        pub fn some(value: String) -> Self {
+            Self(Some(value))
+        }
+
+        pub fn none() -> Self {
+            Self(None)
+        }
+
+        pub fn is_some(&self) -> bool {
+            self.0.is_some()
+        }
+
+        pub fn expose(&self) -> Option<&str> {
+            self.0.as_deref()
+        }
+    }
+
+    impl std::fmt::Debug for Secret {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.0 {
+                Some(_) => write!(f, "Secret(<redacted>)"),
+                None => write!(f, "Secret(None)"),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct EncryptedField {
+        nonce: String,
+        ciphertext: String,
+    }
+
+    impl Serialize for Secret {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            match &self.0 {
+                None => serializer.serialize_none(),
+                Some(plaintext) => {
+                    let encrypted = encrypt(plaintext).map_err(serde::ser::Error::custom)?;
+                    encrypted.serialize(serializer)
+                }
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Secret {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: serde::Deserializer<'de>,
+        {
+            match Option::<EncryptedField>::deserialize(deserializer)? {
+                None => Ok(Secret(None)),
+                Some(encrypted) => {
+                    let plaintext = decrypt(&encrypted).map_err(serde::de::Error::custom)?;
+                    Ok(Secret(Some(plaintext)))
+                }
+            }
+        }
+    }
+
+    fn derive_key_from_passphrase(passphrase: &str) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), KDF_SALT, KDF_ROUNDS, &mut key);
+        key
+    }
+
+    fn load_or_create_key() -> Result<[u8; 32], super::errors::ConfigError> {
+        if let Ok(passphrase) = std::env::var("ALEXA_UTIL_KEY") {
+            return Ok(derive_key_from_passphrase(&passphrase));
+        }
+
+        if KEY_PATH.exists() {
+            let bytes = std::fs::read(&*KEY_PATH)?;
+            if bytes.len() != 32 {
+                return Err(super::errors::ConfigError::DecryptError);
+            }
+
+            let mut key = [0u8; 32];
+            key.copy_from_slice(&bytes);
+            return Ok(key);
+        }
+
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+
+        std::fs::create_dir_all(KEY_PATH.parent().unwrap())?;
+        std::fs::write(&*KEY_PATH, key)?;
+
+        Ok(key)
+    }
+
+    fn cipher() -> Result<Aes256Gcm, super::errors::ConfigError> {
+        let key = load_or_create_key()?;
+        Ok(Aes256Gcm::new_from_slice(&key).expect("key is always 32 bytes"))
+    }
+
+    fn encrypt(plaintext: &str) -> Result<EncryptedField, super::errors::ConfigError> {
+        let cipher = cipher()?;
+
+        let mut nonce_bytes = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce_bytes);
+        let nonce = Nonce::from_slice(&nonce_bytes);
+
+        let ciphertext = cipher
+            .encrypt(nonce, plaintext.as_bytes())
+            .map_err(|_| super::errors::ConfigError::DecryptError)?;
+
+        Ok(EncryptedField {
+            nonce: BASE64.encode(nonce_bytes),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    fn decrypt(encrypted: &EncryptedField) -> Result<String, super::errors::ConfigError> {
+        let cipher = cipher()?;
+
+        let nonce_bytes = BASE64
+            .decode(&encrypted.nonce)
+            .map_err(|_| super::errors::ConfigError::DecryptError)?;
+        let ciphertext = BASE64
+            .decode(&encrypted.ciphertext)
+            .map_err(|_| super::errors::ConfigError::DecryptError)?;
+
+        if nonce_bytes.len() != 12 {
+            return Err(super::errors::ConfigError::DecryptError);
+        }
+        let nonce = Nonce::from_slice(&nonce_bytes);
+
+        let plaintext = cipher
+            .decrypt(nonce, ciphertext.as_ref())
+            .map_err(|_| super::errors::ConfigError::DecryptError)?;
+
+        String::from_utf8(plaintext).map_err(|_| super::errors::ConfigError::DecryptError)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn use_test_key() {
+            std::env::set_var("ALEXA_UTIL_KEY", "test-passphrase-for-config-secret-tests");
+        }
+
+        #[test]
+        fn round_trips_through_encrypt_and_decrypt() {
+            use_test_key();
+
+            let encrypted = encrypt("hunter2").unwrap();
+            let plaintext = decrypt(&encrypted).unwrap();
+
+            assert_eq!(plaintext, "hunter2");
+        }
+
+        #[test]
+        fn rejects_a_nonce_of_the_wrong_length() {
+            use_test_key();
+
+            let mut encrypted = encrypt("hunter2").unwrap();
+            encrypted.nonce = BASE64.encode([0u8; 11]);
+
+            assert!(matches!(
+                decrypt(&encrypted),
+                Err(super::super::errors::ConfigError::DecryptError)
+            ));
+        }
+
+        #[test]
+        fn rejects_tampered_ciphertext() {
+            use_test_key();
+
+            let mut encrypted = encrypt("hunter2").unwrap();
+            let mut ciphertext = BASE64.decode(&encrypted.ciphertext).unwrap();
+            ciphertext[0] ^= 0xff;
+            encrypted.ciphertext = BASE64.encode(ciphertext);
+
+            assert!(matches!(
+                decrypt(&encrypted),
+                Err(super::super::errors::ConfigError::DecryptError)
+            ));
+        }
+
+        #[test]
+        fn secret_serializes_and_deserializes_round_trip() {
+            use_test_key();
+
+            let secret = Secret::some("s3cr3t".to_string());
+            let json = serde_json::to_string(&secret).unwrap();
+            let restored: Secret = serde_json::from_str(&json).unwrap();
+
+            assert_eq!(restored.expose(), Some("s3cr3t"));
+        }
     }
 }
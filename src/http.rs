@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    pub static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+const MAX_ATTEMPTS: u32 = 5;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Sends `request`, retrying with exponential backoff when the response is
+/// a 429 or a 5xx, honoring the `Retry-After` header when the server sends
+/// one, for up to `MAX_ATTEMPTS` attempts. Does not otherwise interpret the
+/// response status, so callers that rely on non-2xx bodies (e.g. OAuth
+/// error payloads) keep working; callers that don't should pass the result
+/// through [`ensure_success`].
+pub async fn execute(
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, errors::RequestError> {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let req = request
+            .try_clone()
+            .ok_or(errors::RequestError::NotRetryable)?;
+
+        let res = req.send().await?;
+        let status = res.status();
+        let retryable = status.as_u16() == 429 || status.is_server_error();
+
+        if !retryable || attempt >= MAX_ATTEMPTS {
+            return Ok(res);
+        }
+
+        let backoff =
+            retry_after(&res).unwrap_or_else(|| BASE_BACKOFF * 2u32.pow(attempt - 1));
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+/// Turns a non-2xx response into a typed [`errors::RequestError::Status`]
+/// carrying the status code and response body instead of letting the
+/// caller attempt to parse it as a successful body.
+pub async fn ensure_success(
+    res: reqwest::Response,
+) -> Result<reqwest::Response, errors::RequestError> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+
+    let status = res.status().as_u16();
+    let body = res.text().await.unwrap_or_default();
+
+    Err(errors::RequestError::Status { status, body })
+}
+
+fn retry_after(res: &reqwest::Response) -> Option<Duration> {
+    res.headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+pub mod errors {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum RequestError {
+        #[error("Failed to make the request.")]
+        FetchError(#[from] reqwest::Error),
+
+        #[error("Request could not be cloned for retry")]
+        NotRetryable,
+
+        #[error("Request failed with status {status}: {body}")]
+        Status { status: u16, body: String },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serves `responses` in order, one per accepted connection, on a
+    /// one-shot-per-request localhost listener. Returns the base URL and a
+    /// counter of how many requests were actually received.
+    fn spawn_server(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let count_clone = Arc::clone(&count);
+
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                count_clone.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (format!("http://{}", addr), count)
+    }
+
+    #[tokio::test]
+    async fn ensure_success_passes_through_2xx() {
+        let (base_url, _count) =
+            spawn_server(vec!["HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"]);
+
+        let res = HTTP_CLIENT.get(&base_url).send().await.unwrap();
+        let res = ensure_success(res).await.unwrap();
+
+        assert_eq!(res.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn ensure_success_turns_non_2xx_into_status_error() {
+        let (base_url, _count) = spawn_server(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found",
+        ]);
+
+        let res = HTTP_CLIENT.get(&base_url).send().await.unwrap();
+        let err = ensure_success(res).await.unwrap_err();
+
+        match err {
+            errors::RequestError::Status { status, body } => {
+                assert_eq!(status, 404);
+                assert_eq!(body, "not found");
+            }
+            other => panic!("expected Status error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_does_not_retry_a_non_retryable_status() {
+        let (base_url, count) = spawn_server(vec![
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n",
+        ]);
+
+        let res = execute(HTTP_CLIENT.get(&base_url)).await.unwrap();
+
+        assert_eq!(res.status(), 400);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn execute_retries_a_429_until_it_succeeds() {
+        let (base_url, count) = spawn_server(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+        ]);
+
+        let res = execute(HTTP_CLIENT.get(&base_url)).await.unwrap();
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod apis;
+pub mod auth;
+pub mod config;
+pub mod http;